@@ -0,0 +1,49 @@
+//! A single self-contained deploy artifact produced by `we build --bundle`, embedding the wasm
+//! bytecode, its hash, the generated metadata, the contract's package version and the rustc
+//! version that produced it, so a
+//! contract is distributed as one file rather than the loose wasm/json pair.
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+
+use crate::metadata::Metadata;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub bytecode: String,
+    pub bytecode_hash: String,
+    pub metadata: Metadata,
+    pub contract_version: String,
+    pub rustc_version: String,
+}
+
+impl Bundle {
+    pub fn new(bytecode: &[u8], metadata: Metadata, contract_version: String, rustc_version: String) -> Self {
+        Bundle {
+            bytecode: general_purpose::STANDARD.encode(bytecode),
+            bytecode_hash: digest(bytecode),
+            metadata,
+            contract_version,
+            rustc_version,
+        }
+    }
+
+    pub fn as_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize bundle")
+    }
+}
+
+/// The `rustc +nightly --version` output, recorded in the bundle alongside the contract version
+/// since the build always compiles with the nightly toolchain.
+pub fn rustc_version() -> String {
+    let output = std::process::Command::new("rustc")
+        .args(["+nightly", "--version"])
+        .output()
+        .expect("Failed to run `rustc +nightly --version`");
+
+    String::from_utf8(output.stdout)
+        .expect("rustc version output is not valid utf-8")
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,279 @@
+//! Embedded wasmtime host used by `we test` to run a contract off-chain.
+//!
+//! The host re-implements, in plain Rust, every import the `interface`/`action`
+//! macros rely on (`call_arg_*`, `call_payment`, `call_contract`, `get_caller`, and the
+//! `get_storage`/`set_storage` family) against an in-memory key/value store, so
+//! a contract can be exercised without a live node.
+
+use std::collections::HashMap;
+
+use wasmtime::{Caller, Engine, Linker, Memory, MemoryType, Module, Store};
+
+use crate::{INITIAL_MEMORY_PAGES, MAX_MEMORY_PAGES};
+
+/// Mocked chain state a test run is executed against.
+#[derive(Default)]
+pub struct MockState {
+    pub caller: Vec<u8>,
+    pub payments: Vec<(Vec<u8>, i64)>,
+    pub storage_integer: HashMap<String, i64>,
+    pub storage_boolean: HashMap<String, bool>,
+    pub storage_binary: HashMap<String, Vec<u8>>,
+    pub storage_string: HashMap<String, String>,
+    pending_args: Vec<ArgValue>,
+    pub calls: Vec<String>,
+    memory: Option<Memory>,
+}
+
+enum ArgValue {
+    Integer(i64),
+    Boolean(bool),
+    Binary(Vec<u8>),
+    String(String),
+}
+
+fn memory(caller: &mut Caller<'_, MockState>) -> Memory {
+    caller
+        .data()
+        .memory
+        .expect("contract memory was not defined before instantiation")
+}
+
+fn read_bytes(caller: &mut Caller<'_, MockState>, ptr: i32, len: i32) -> Vec<u8> {
+    let memory = memory(caller);
+    let mut buf = vec![0u8; len as usize];
+    memory
+        .read(caller, ptr as usize, &mut buf)
+        .expect("out of bounds memory access");
+    buf
+}
+
+fn read_string(caller: &mut Caller<'_, MockState>, ptr: i32, len: i32) -> String {
+    String::from_utf8(read_bytes(caller, ptr, len)).expect("key/value is not valid utf-8")
+}
+
+fn write_bytes(caller: &mut Caller<'_, MockState>, ptr: i32, data: &[u8]) {
+    let memory = memory(caller);
+    memory
+        .write(caller, ptr as usize, data)
+        .expect("out of bounds memory access");
+}
+
+/// Every host import the mock `linker` below provides. `wasm_opt::validate` does not check a
+/// contract's imports against this list.
+pub const IMPORTS: &[&str] = &[
+    "call_arg_int",
+    "call_arg_bool",
+    "call_arg_binary",
+    "call_arg_string",
+    "call_payment",
+    "call_contract",
+    "get_caller",
+    "get_storage_integer",
+    "set_storage_integer",
+    "get_storage_boolean",
+    "set_storage_boolean",
+    "get_storage_binary",
+    "set_storage_binary",
+    "get_storage_string",
+    "set_storage_string",
+];
+
+/// Builds a `Linker` providing every host import the SDK's generated code calls.
+pub fn linker(engine: &Engine) -> Linker<MockState> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap("env", "call_arg_int", |mut caller: Caller<'_, MockState>, value: i64| {
+            caller.data_mut().pending_args.push(ArgValue::Integer(value));
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("env", "call_arg_bool", |mut caller: Caller<'_, MockState>, value: i32| {
+            caller.data_mut().pending_args.push(ArgValue::Boolean(value != 0));
+        })
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "call_arg_binary",
+            |mut caller: Caller<'_, MockState>, ptr: i32, len: i32| {
+                let bytes = read_bytes(&mut caller, ptr, len);
+                caller.data_mut().pending_args.push(ArgValue::Binary(bytes));
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "call_arg_string",
+            |mut caller: Caller<'_, MockState>, ptr: i32, len: i32| {
+                let string = read_string(&mut caller, ptr, len);
+                caller.data_mut().pending_args.push(ArgValue::String(string));
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "call_payment",
+            |mut caller: Caller<'_, MockState>, asset_ptr: i32, asset_len: i32, amount: i64| {
+                let asset_id = read_bytes(&mut caller, asset_ptr, asset_len);
+                caller.data_mut().payments.push((asset_id, amount));
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "call_contract",
+            |mut caller: Caller<'_, MockState>,
+             contract_ptr: i32,
+             contract_len: i32,
+             method_ptr: i32,
+             method_len: i32|
+             -> i32 {
+                let contract_id = read_bytes(&mut caller, contract_ptr, contract_len);
+                let method = read_string(&mut caller, method_ptr, method_len);
+                let state = caller.data_mut();
+                state.calls.push(format!("{}::{}", hex::encode(&contract_id), method));
+                state.pending_args.clear();
+                0
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "get_caller",
+            |mut caller: Caller<'_, MockState>, out_ptr: i32| -> i32 {
+                let value = caller.data().caller.clone();
+                let len = value.len() as i32;
+                write_bytes(&mut caller, out_ptr, &value);
+                len
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "get_storage_integer",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32| -> i64 {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                *caller.data().storage_integer.get(&key).unwrap_or(&0)
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "set_storage_integer",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, value: i64| {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                caller.data_mut().storage_integer.insert(key, value);
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "get_storage_boolean",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32| -> i32 {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                caller.data().storage_boolean.get(&key).copied().unwrap_or(false) as i32
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "set_storage_boolean",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, value: i32| {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                caller.data_mut().storage_boolean.insert(key, value != 0);
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "get_storage_binary",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, out_ptr: i32| -> i32 {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                let value = caller.data().storage_binary.get(&key).cloned().unwrap_or_default();
+                let len = value.len() as i32;
+                write_bytes(&mut caller, out_ptr, &value);
+                len
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "set_storage_binary",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, ptr: i32, len: i32| {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                let value = read_bytes(&mut caller, ptr, len);
+                caller.data_mut().storage_binary.insert(key, value);
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "get_storage_string",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, out_ptr: i32| -> i32 {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                let value = caller.data().storage_string.get(&key).cloned().unwrap_or_default();
+                let len = value.len() as i32;
+                write_bytes(&mut caller, out_ptr, value.as_bytes());
+                len
+            },
+        )
+        .unwrap();
+
+    linker
+        .func_wrap(
+            "env",
+            "set_storage_string",
+            |mut caller: Caller<'_, MockState>, key_ptr: i32, key_len: i32, ptr: i32, len: i32| {
+                let key = read_string(&mut caller, key_ptr, key_len);
+                let value = read_string(&mut caller, ptr, len);
+                caller.data_mut().storage_string.insert(key, value);
+            },
+        )
+        .unwrap();
+
+    linker
+}
+
+/// Loads `path` and returns a `(Module, Store)` pair ready for instantiation against `linker`.
+pub fn load(engine: &Engine, path: &std::path::Path, state: MockState) -> (Module, Store<MockState>) {
+    let module = Module::from_file(engine, path).expect("Failed to load contract wasm");
+    let store = Store::new(engine, state);
+    (module, store)
+}
+
+/// Creates the `env.memory` the contract imports (the build links with `--import-memory`) and
+/// defines it into `linker`, so instantiation doesn't fail on an unresolved import.
+pub fn define_memory(linker: &mut Linker<MockState>, store: &mut Store<MockState>) {
+    let memory_ty = MemoryType::new(INITIAL_MEMORY_PAGES as u32, Some(MAX_MEMORY_PAGES as u32));
+    let memory = Memory::new(&mut *store, memory_ty).expect("Failed to create contract memory");
+    store.data_mut().memory = Some(memory);
+    linker
+        .define(&mut *store, "env", "memory", memory)
+        .expect("Failed to define `env.memory`");
+}
@@ -1,5 +1,8 @@
+mod bundle;
+mod host;
 mod metadata;
 pub mod node;
+mod wasm_opt;
 
 use base64::{
     alphabet,
@@ -11,6 +14,7 @@ use sha256::digest;
 
 use cargo_metadata::{semver::Version, Message, MetadataCommand};
 use clap::{builder::Str, Args, Parser, Subcommand};
+use host::MockState;
 use metadata::Metadata;
 use node::transactions::{self, *};
 use std::{
@@ -20,9 +24,19 @@ use std::{
     process::{Command, Stdio},
 };
 use syn::Data;
+use wasmtime::{Engine, Linker, Store};
 
 const TARGET_WE: &str = "target/we";
 
+/// Wasm memory page size (64 KiB), as defined by the spec.
+pub const WASM_PAGE_SIZE: u64 = 65_536;
+
+/// Initial/maximum memory pages passed to the linker below via `--initial-memory`/`--max-memory`
+/// (in bytes), and enforced by `wasm_opt::validate` and mocked by `host::define_memory`. Keeping
+/// all three in terms of these constants is what keeps them from drifting apart.
+pub const INITIAL_MEMORY_PAGES: u64 = 131_072 / WASM_PAGE_SIZE;
+pub const MAX_MEMORY_PAGES: u64 = 1_048_576 / WASM_PAGE_SIZE;
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 enum Cli {
@@ -50,7 +64,15 @@ enum Action {
     },
     /// Compiles the contract.
     #[clap(name = "build")]
-    Build,
+    Build {
+        /// Emit a single self-contained artifact (wasm, metadata and hash) in place of the
+        /// metadata json; the wasm itself is still written to `target/we` beside it.
+        #[clap(long)]
+        bundle: bool,
+    },
+    /// Compiles the contract without emitting the `target/we` artifacts, to surface errors fast.
+    #[clap(name = "check")]
+    Check,
     /// Converts from the text format to the binary format.
     #[clap(name = "wat2wasm")]
     Wat2Wasm {
@@ -67,6 +89,30 @@ enum Action {
         #[clap(short, long, value_parser)]
         output: Option<PathBuf>,
     },
+    /// Runs a contract action off-chain against a mocked host.
+    #[clap(name = "test")]
+    Test {
+        /// The exported action to invoke, e.g. `_constructor`.
+        action: String,
+        /// Typed arguments passed to the action, e.g. `int:42`, `bool:true` (the generated
+        /// `action` export only takes Integer/Boolean parameters directly).
+        #[clap(long = "arg")]
+        args: Vec<String>,
+        /// The calling address, as hex, seeded before invoking the action.
+        #[clap(long)]
+        caller: Option<String>,
+        /// An attached payment as `asset_id_hex:amount`, may be repeated.
+        #[clap(long = "payment")]
+        payments: Vec<String>,
+        /// Initial storage entry as `kind:key=value` (kind: int, bool, binary, string), seeded
+        /// before invoking the action. May be repeated.
+        #[clap(long = "storage")]
+        storage: Vec<String>,
+        /// Expected storage entry after the call, as `kind:key=value`; the command exits
+        /// non-zero if the actual value differs. May be repeated.
+        #[clap(long = "expect")]
+        expect: Vec<String>,
+    },
     /// Deploy new contract by using Sign and Broadcast.
     #[clap(name = "create")]
     Create {
@@ -91,9 +137,18 @@ async fn main() -> Result<(), Error> {
 
     match args.action {
         Action::New { name, target_dir } => new(name, target_dir),
-        Action::Build => build(),
+        Action::Build { bundle } => build(bundle),
+        Action::Check => check(),
         Action::Wat2Wasm { filename, output } => wat2wasm(filename, output),
         Action::Wasm2Wat { filename, output } => wasm2wat(filename, output),
+        Action::Test {
+            action,
+            args,
+            caller,
+            payments,
+            storage,
+            expect,
+        } => test(action, args, caller, payments, storage, expect),
         Action::Create { flag, path_json } => create(flag, path_json).await,
         Action::Update { flag, path_json } => update(flag, path_json).await,
     }
@@ -214,7 +269,26 @@ Cargo.lock
     Ok(())
 }
 
-fn build() -> Result<(), Error> {
+/// Whether `cargo_build` should produce final `target/we` artifacts or just check compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildMode {
+    Build,
+    Check,
+}
+
+fn build(bundle: bool) -> Result<(), Error> {
+    cargo_build(BuildMode::Build, bundle)
+}
+
+fn check() -> Result<(), Error> {
+    cargo_build(BuildMode::Check, false)
+}
+
+/// Runs the shared nightly `cargo` invocation (target, build-std and linker rustflags) and, in
+/// `BuildMode::Build`, renames the produced wasm into `target/we` and writes its metadata JSON,
+/// or, when `bundle` is set, a single `<name>.bundle.json` embedding the wasm, its hash and the
+/// metadata in place of that metadata JSON (the wasm is written either way).
+fn cargo_build(mode: BuildMode, bundle: bool) -> Result<(), Error> {
     let metadata = MetadataCommand::new()
         .manifest_path("Cargo.toml")
         .exec()
@@ -226,17 +300,34 @@ fn build() -> Result<(), Error> {
         .name
         .as_str();
 
-    fs::create_dir_all(TARGET_WE)?;
+    if mode == BuildMode::Build {
+        fs::create_dir_all(TARGET_WE)?;
+    }
+
+    let cargo_subcommand = match mode {
+        BuildMode::Build => "build",
+        BuildMode::Check => "check",
+    };
+
+    let link_args = format!(
+        "link-args=--no-entry --import-memory -zstack-size=16 --initial-memory={} --max-memory={}",
+        INITIAL_MEMORY_PAGES * WASM_PAGE_SIZE,
+        MAX_MEMORY_PAGES * WASM_PAGE_SIZE,
+    );
+    let rustflags_config = format!(
+        "--config=target.wasm32-unknown-unknown.rustflags = [\"-C\", \"target-feature=+bulk-memory,+multivalue\", \"-C\", \"{}\"]",
+        link_args
+    );
 
     let mut command = Command::new("cargo")
             .args([
-                "+nightly",
-                "build",
-                "--release",
-                "--message-format=json-render-diagnostics",
-                "-Zbuild-std=std,panic_abort",
-                "--target=wasm32-unknown-unknown",
-                "--config=target.wasm32-unknown-unknown.rustflags = [\"-C\", \"target-feature=+bulk-memory,+multivalue\", \"-C\", \"link-args=--no-entry --import-memory -zstack-size=16 --initial-memory=131072 --max-memory=1048576\"]"
+                "+nightly".to_string(),
+                cargo_subcommand.to_string(),
+                "--release".to_string(),
+                "--message-format=json-render-diagnostics".to_string(),
+                "-Zbuild-std=std,panic_abort".to_string(),
+                "--target=wasm32-unknown-unknown".to_string(),
+                rustflags_config,
             ])
             .stdout(Stdio::piped())
             .spawn()?;
@@ -247,7 +338,10 @@ fn build() -> Result<(), Error> {
     for message in cargo_metadata::Message::parse_stream(reader) {
         match message.expect("Unable to get message") {
             Message::CompilerArtifact(artifact) => {
-                if artifact.target.name == project_name && !artifact.filenames.is_empty() {
+                if mode == BuildMode::Build
+                    && artifact.target.name == project_name
+                    && !artifact.filenames.is_empty()
+                {
                     if let Some(file_name) = artifact.filenames[0].file_name() {
                         fs::rename(
                             &artifact.filenames[0],
@@ -257,16 +351,47 @@ fn build() -> Result<(), Error> {
                 }
             }
             Message::BuildFinished(finished) => {
-                if finished.success {
-                    let json = Metadata::new(project_name).as_json();
-
-                    let mut metadata_file = fs::OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(format!("{}/{}.json", TARGET_WE, project_name))?;
-
-                    write!(metadata_file, "{}", json)?;
+                if mode == BuildMode::Build && finished.success {
+                    let path_wasm = format!("{}/{}.wasm", TARGET_WE, project_name);
+                    let bytecode = fs::read(&path_wasm)?;
+                    wasm_opt::validate(&bytecode)?;
+                    let bytecode = wasm_opt::shrink(&bytecode);
+                    fs::write(&path_wasm, &bytecode)?;
+
+                    let contract_metadata = Metadata::new(project_name);
+
+                    if bundle {
+                        let contract_version = metadata
+                            .root_package()
+                            .expect("Unable to get root package")
+                            .version
+                            .to_string();
+                        let json = bundle::Bundle::new(
+                            &bytecode,
+                            contract_metadata,
+                            contract_version,
+                            bundle::rustc_version(),
+                        )
+                        .as_json();
+
+                        let mut bundle_file = fs::OpenOptions::new()
+                            .create(true)
+                            .write(true)
+                            .truncate(true)
+                            .open(format!("{}/{}.bundle.json", TARGET_WE, project_name))?;
+
+                        write!(bundle_file, "{}", json)?;
+                    } else {
+                        let json = contract_metadata.as_json();
+
+                        let mut metadata_file = fs::OpenOptions::new()
+                            .create(true)
+                            .write(true)
+                            .truncate(true)
+                            .open(format!("{}/{}.json", TARGET_WE, project_name))?;
+
+                        write!(metadata_file, "{}", json)?;
+                    }
                 }
             }
             _ => (),
@@ -278,6 +403,161 @@ fn build() -> Result<(), Error> {
     Ok(())
 }
 
+fn test(
+    action: String,
+    args: Vec<String>,
+    caller: Option<String>,
+    payments: Vec<String>,
+    storage: Vec<String>,
+    expect: Vec<String>,
+) -> Result<(), Error> {
+    let metadata = MetadataCommand::new()
+        .manifest_path("Cargo.toml")
+        .exec()
+        .expect("Unable to runs `cargo metadata`");
+
+    let project_name = metadata
+        .root_package()
+        .expect("Unable to get root package")
+        .name
+        .as_str();
+
+    rebuild_if_stale()?;
+    let path_wasm = PathBuf::from(format!("{}/{}.wasm", TARGET_WE, project_name));
+
+    let mut state = MockState::default();
+    if let Some(caller) = caller {
+        state.caller = hex::decode(caller).expect("Invalid hex caller address");
+    }
+    for payment in &payments {
+        let (asset_id, amount) = payment.split_once(':').unwrap_or_else(|| {
+            panic!("Payment `{}` must be in the form `asset_id_hex:amount`", payment)
+        });
+        state.payments.push((
+            hex::decode(asset_id).expect("Invalid hex asset id"),
+            amount.parse().expect("Invalid payment amount"),
+        ));
+    }
+    for entry in &storage {
+        seed_storage(&mut state, entry);
+    }
+
+    let engine = Engine::default();
+    let mut linker: Linker<MockState> = host::linker(&engine);
+    let (module, mut store) = host::load(&engine, &path_wasm, state);
+    host::define_memory(&mut linker, &mut store);
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .expect("Failed to instantiate contract wasm");
+
+    let func = instance
+        .get_func(&mut store, &action)
+        .unwrap_or_else(|| panic!("Action `{}` is not exported by the contract", action));
+
+    let params = args
+        .iter()
+        .map(|arg| parse_action_arg(arg))
+        .collect::<Vec<_>>();
+
+    let mut results = vec![wasmtime::Val::I32(0); func.ty(&store).results().len()];
+    func.call(&mut store, &params, &mut results)
+        .expect("Action call trapped");
+
+    println!("Action `{}` returned {:?}", action, results);
+    println!("storage(integer) = {:?}", store.data().storage_integer);
+    println!("storage(boolean) = {:?}", store.data().storage_boolean);
+    println!("storage(binary)  = {:?}", store.data().storage_binary);
+    println!("storage(string)  = {:?}", store.data().storage_string);
+
+    let failures: Vec<String> = expect
+        .iter()
+        .filter_map(|entry| check_storage_expectation(&store, entry).err())
+        .collect();
+
+    if !failures.is_empty() {
+        for failure in &failures {
+            eprintln!("assertion failed: {}", failure);
+        }
+        return Err(Error::other(format!("{} assertion(s) failed", failures.len())));
+    }
+
+    Ok(())
+}
+
+/// Parses a `kind:value` CLI argument (as accepted by `we test --arg`) into a wasmtime value.
+fn parse_action_arg(arg: &str) -> wasmtime::Val {
+    let (kind, value) = arg
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Argument `{}` must be in the form `kind:value`", arg));
+
+    match kind {
+        "int" => wasmtime::Val::I64(value.parse().expect("Invalid integer argument")),
+        "bool" => wasmtime::Val::I32(value.parse::<bool>().expect("Invalid boolean argument") as i32),
+        _ => panic!("Unsupported argument kind `{}`", kind),
+    }
+}
+
+/// Parses a `kind:key=value` CLI argument (as accepted by `we test --storage`) and seeds it into
+/// `state`.
+fn seed_storage(state: &mut MockState, entry: &str) {
+    let (kind, key, value) = split_storage_entry(entry);
+
+    match kind {
+        "int" => {
+            state
+                .storage_integer
+                .insert(key.to_string(), value.parse().expect("Invalid integer value"));
+        }
+        "bool" => {
+            state
+                .storage_boolean
+                .insert(key.to_string(), value.parse().expect("Invalid boolean value"));
+        }
+        "binary" => {
+            state
+                .storage_binary
+                .insert(key.to_string(), hex::decode(value).expect("Invalid hex value"));
+        }
+        "string" => {
+            state.storage_string.insert(key.to_string(), value.to_string());
+        }
+        _ => panic!("Unsupported storage kind `{}`", kind),
+    }
+}
+
+/// Checks a `kind:key=value` CLI argument (as accepted by `we test --expect`) against the
+/// contract's final storage.
+fn check_storage_expectation(store: &Store<MockState>, entry: &str) -> Result<(), String> {
+    let (kind, key, expected) = split_storage_entry(entry);
+
+    let actual = match kind {
+        "int" => store.data().storage_integer.get(key).map(|value| value.to_string()),
+        "bool" => store.data().storage_boolean.get(key).map(|value| value.to_string()),
+        "binary" => store.data().storage_binary.get(key).map(hex::encode),
+        "string" => store.data().storage_string.get(key).cloned(),
+        _ => panic!("Unsupported storage kind `{}`", kind),
+    };
+
+    match actual {
+        Some(actual) if actual == expected => Ok(()),
+        Some(actual) => Err(format!("storage `{}` = `{}`, expected `{}`", key, actual, expected)),
+        None => Err(format!("storage `{}` was never set, expected `{}`", key, expected)),
+    }
+}
+
+/// Splits a `kind:key=value` CLI argument into its three parts.
+fn split_storage_entry(entry: &str) -> (&str, &str, &str) {
+    let (kind, rest) = entry
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Storage entry `{}` must be in the form `kind:key=value`", entry));
+    let (key, value) = rest
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Storage entry `{}` must be in the form `kind:key=value`", entry));
+
+    (kind, key, value)
+}
+
 fn wat2wasm(filename: PathBuf, output: Option<PathBuf>) -> Result<(), Error> {
     let output = match output {
         Some(path) => path
@@ -326,6 +606,7 @@ fn wasm2wat(filename: PathBuf, output: Option<PathBuf>) -> Result<(), Error> {
 async fn create(flag: Option<bool>, path_json: PathBuf) -> Result<(), Error> {
     let file = fs::read_to_string(path_json).expect("Can't read file");
     let config: Config = serde_json::from_str::<Config>(&file).expect("Can't parse json");
+    ensure_built(&config.transaction.stored_contract)?;
     let transaction_create = transaction_create(&config, 107, 6);
 
     let node = node::Node::from_url(config.node_url);
@@ -348,6 +629,7 @@ async fn create(flag: Option<bool>, path_json: PathBuf) -> Result<(), Error> {
 async fn update(flag: Option<bool>, path_json: PathBuf) -> Result<(), Error> {
     let file = fs::read_to_string(path_json).expect("Can't read file");
     let config: Config = serde_json::from_str::<Config>(&file).expect("Can't parse json");
+    ensure_built(&config.transaction.stored_contract)?;
     let transaction_create = transaction_create(&config, 107, 6);
 
     let node = node::Node::from_url(config.node_url);
@@ -367,6 +649,97 @@ async fn update(flag: Option<bool>, path_json: PathBuf) -> Result<(), Error> {
     }
 }
 
+/// Runs `build()` before a deploy unless `stored_contract` already supplies the bytecode,
+/// skipping the rebuild when `target/we/<name>.wasm` is already newer than `Cargo.toml`,
+/// `Cargo.lock` and every file in `src`.
+fn ensure_built(stored_contract: &Option<StoredContractWasm>) -> Result<(), Error> {
+    if stored_contract.is_some() {
+        return Ok(());
+    }
+
+    rebuild_if_stale()
+}
+
+/// Runs `build()` when `target/we/<name>.wasm` is missing or stale, shared by `ensure_built` (the
+/// `create`/`update` foot-gun) and `test`, which reads the same artifact.
+fn rebuild_if_stale() -> Result<(), Error> {
+    let metadata = MetadataCommand::new()
+        .manifest_path("Cargo.toml")
+        .exec()
+        .expect("Unable to runs `cargo metadata`");
+
+    let project_name = metadata
+        .root_package()
+        .expect("Unable to get root package")
+        .name
+        .as_str();
+
+    let path_wasm = PathBuf::from(format!("{}/{}.wasm", TARGET_WE, project_name));
+
+    if is_stale(&path_wasm) {
+        build(false)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `path_wasm` is missing or older than `Cargo.toml`, `Cargo.lock`, or any file anywhere
+/// under `src`.
+fn is_stale(path_wasm: &Path) -> bool {
+    let wasm_modified = match fs::metadata(path_wasm).and_then(|file| file.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return true,
+    };
+
+    for manifest in ["Cargo.toml", "Cargo.lock"] {
+        if let Ok(modified) = fs::metadata(manifest).and_then(|file| file.modified()) {
+            if modified > wasm_modified {
+                return true;
+            }
+        }
+    }
+
+    any_file_newer_than(Path::new("src"), wasm_modified)
+}
+
+/// Recursively checks whether any file under `dir` was modified after `since`.
+fn any_file_newer_than(dir: &Path, since: std::time::SystemTime) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            if any_file_newer_than(&entry.path(), since) {
+                return true;
+            }
+        } else if let Ok(modified) = entry.metadata().and_then(|file| file.modified()) {
+            if modified > since {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// Whether `path` exists and is at least as new as `other`, so a rebuilt `.wasm` that left a
+/// stale `.bundle.json` behind isn't silently preferred over it.
+fn is_newer(path: &Path, other: &Path) -> bool {
+    let Ok(modified) = fs::metadata(path).and_then(|file| file.modified()) else {
+        return false;
+    };
+
+    match fs::metadata(other).and_then(|file| file.modified()) {
+        Ok(other_modified) => modified >= other_modified,
+        Err(_) => true,
+    }
+}
+
 fn check_stored_contract(_stored_contract: Option<StoredContractWasm>) -> StoredContractWasm {
     match _stored_contract {
         Some(str_contract) => str_contract,
@@ -381,7 +754,20 @@ fn check_stored_contract(_stored_contract: Option<StoredContractWasm>) -> Stored
                 .expect("Unable to get root package")
                 .name
                 .as_str();
+
+            let path_bundle = format!("{}/{}.bundle.json", TARGET_WE, project_name);
             let path_wasm = format!("{}/{}.wasm", TARGET_WE, project_name);
+
+            if is_newer(Path::new(&path_bundle), Path::new(&path_wasm)) {
+                let bundle_json = fs::read_to_string(&path_bundle).expect("Can't read file");
+                let bundle: bundle::Bundle =
+                    serde_json::from_str(&bundle_json).expect("Can't parse bundle");
+                return StoredContractWasm {
+                    bytecode: bundle.bytecode,
+                    bytecode_hash: bundle.bytecode_hash,
+                };
+            }
+
             let bytecode = fs::read(path_wasm).expect("Can't read file");
             let bytecode_hash = digest(bytecode.clone());
             StoredContractWasm {
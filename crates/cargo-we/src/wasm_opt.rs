@@ -0,0 +1,104 @@
+//! Post-build validation and size-reduction pass run over the artifact in `target/we`.
+//!
+//! The pipeline hardcodes the linker's memory limits but never inspected the resulting module;
+//! this checks declared memory stays within the node's page limit, then strips non-essential
+//! custom sections to shrink the binary before it is written to `target/we`.
+
+use std::io::Error;
+
+use wasmparser::{Parser, Payload, TypeRef};
+
+use crate::MAX_MEMORY_PAGES;
+
+/// Fails with a clear diagnostic if `bytecode` declares more memory than the node allows. The
+/// build links with `--import-memory`, so the memory to check normally arrives as an import
+/// rather than a `MemorySection` entry; both are checked since either one can be present
+/// depending on how the module was linked.
+///
+/// This intentionally does not police which host functions are imported: `host::IMPORTS` is the
+/// subset the `we test` mock implements, not the node's full ABI, so a contract calling a real
+/// node import (transfers, crypto, block info, ...) that the mock doesn't happen to implement
+/// must still build successfully.
+pub fn validate(bytecode: &[u8]) -> Result<(), Error> {
+    let check_memory = |maximum: Option<u64>| -> Result<(), Error> {
+        if let Some(maximum) = maximum {
+            if maximum > MAX_MEMORY_PAGES {
+                return Err(Error::other(format!(
+                    "contract declares a maximum of {} memory pages, the node allows at most {}",
+                    maximum, MAX_MEMORY_PAGES
+                )));
+            }
+        }
+        Ok(())
+    };
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        match payload.map_err(to_io_error)? {
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    check_memory(memory.map_err(to_io_error)?.maximum)?;
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Memory(memory) = import.map_err(to_io_error)?.ty {
+                        check_memory(memory.maximum)?;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Strips non-essential custom sections (debug names, producer info, ...) from `bytecode` and
+/// reports the before/after size on stdout, since deploy cost is size-sensitive.
+pub fn shrink(bytecode: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytecode.len());
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(_) => return bytecode.to_vec(),
+        };
+
+        match payload {
+            Payload::Version { .. } => output.extend_from_slice(&bytecode[0..8]),
+            Payload::CustomSection(_) | Payload::End(_) => (),
+            other => {
+                if let Some((id, range)) = other.as_section() {
+                    output.push(id);
+                    write_leb128_u32(&mut output, (range.end - range.start) as u32);
+                    output.extend_from_slice(&bytecode[range]);
+                }
+            }
+        }
+    }
+
+    println!(
+        "Shrunk contract from {} to {} bytes",
+        bytecode.len(),
+        output.len()
+    );
+
+    output
+}
+
+fn write_leb128_u32(buf: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            buf.push(byte | 0x80);
+        } else {
+            buf.push(byte);
+            break;
+        }
+    }
+}
+
+fn to_io_error<E: std::fmt::Display>(err: E) -> Error {
+    Error::other(err.to_string())
+}